@@ -55,7 +55,7 @@ use prelude::*;
 
 use num;
 use vec;
-use super::{Stream, Decorator};
+use super::{Stream, Decorator, Buffer, Seek, SeekStyle, SeekSet, SeekEnd, SeekCur};
 
 // libuv recommends 64k buffers to maximize throughput
 // https://groups.google.com/forum/#!topic/libuv/oQO1HJAIDdA
@@ -92,6 +92,19 @@ impl<R: Reader> BufferedReader<R> {
     pub fn new(inner: R) -> BufferedReader<R> {
         BufferedReader::with_capacity(DEFAULT_CAPACITY, inner)
     }
+
+    /// Converts this reader into an iterator over the lines of the
+    /// underlying reader, decoded as UTF-8.
+    pub fn lines(self) -> Lines<BufferedReader<R>> {
+        Lines { buffer: self }
+    }
+
+    /// Converts this reader into an iterator over `byte`-delimited chunks of
+    /// the underlying reader.
+    pub fn split(self, byte: u8) -> Split<BufferedReader<R>> {
+        Split { buffer: self, delim: byte }
+    }
+
 }
 
 impl<R: Reader> Buffer for BufferedReader<R> {
@@ -112,6 +125,10 @@ impl<R: Reader> Buffer for BufferedReader<R> {
         self.pos += amt;
         assert!(self.pos <= self.cap);
     }
+
+    fn buffer_len(&self) -> uint { self.cap - self.pos }
+
+    fn capacity(&self) -> uint { self.buf.len() }
 }
 
 impl<R: Reader> Reader for BufferedReader<R> {
@@ -140,11 +157,109 @@ impl<R: Reader> Decorator<R> for BufferedReader<R> {
     fn inner_mut_ref<'a>(&'a mut self) -> &'a mut R { &mut self.inner }
 }
 
+impl<R: Reader + Seek> Seek for BufferedReader<R> {
+    fn tell(&self) -> u64 {
+        let bytes_ahead = (self.cap - self.pos) as u64;
+        self.inner.tell() - bytes_ahead
+    }
+
+    fn seek(&mut self, pos: i64, style: SeekStyle) {
+        let available = (self.cap - self.pos) as i64;
+        match style {
+            // A forward-only move that stays inside the bytes we've already
+            // buffered can be satisfied without touching the inner reader.
+            SeekCur if pos >= 0 && pos <= available => {
+                self.pos += pos as uint;
+                return;
+            }
+            _ => {}
+        }
+
+        // Otherwise the buffer is no longer coherent with the requested
+        // position, so drop it and reissue the seek against the inner
+        // reader, adjusting a `SeekCur` offset for the bytes we'd already
+        // buffered ahead of its position.
+        let pos = match style {
+            SeekCur => pos - available,
+            SeekSet | SeekEnd => pos,
+        };
+        self.pos = 0;
+        self.cap = 0;
+        self.inner.seek(pos, style);
+    }
+}
+
+/// An iterator over the lines of an instance of `B`, decoded as UTF-8.
+///
+/// This iterator is built only on top of `Buffer`'s `read_line`, so it works
+/// the same for any buffered reader or stream.
+pub struct Lines<B> {
+    priv buffer: B,
+}
+
+impl<B: Buffer> Iterator<~str> for Lines<B> {
+    fn next(&mut self) -> Option<~str> {
+        self.buffer.read_line()
+    }
+}
+
+/// An iterator over `byte`-delimited chunks of an instance of `B`. The
+/// delimiter byte itself is not included in the yielded chunks.
+pub struct Split<B> {
+    priv buffer: B,
+    priv delim: u8,
+}
+
+impl<B: Buffer> Iterator<~[u8]> for Split<B> {
+    fn next(&mut self) -> Option<~[u8]> {
+        let delim = self.delim;
+        match self.buffer.read_until(delim) {
+            Some(mut chunk) => {
+                if chunk.len() > 0 && chunk[chunk.len() - 1] == delim {
+                    chunk.pop();
+                }
+                Some(chunk)
+            }
+            None => None,
+        }
+    }
+}
+
+/// The error a final flush inside `into_inner` could fail with.
+///
+/// `Writer::write`/`Writer::flush` in this io layer are infallible (they
+/// return `()`), so there is currently nothing that could ever populate a
+/// value of this type — it is an empty enum. It exists so `IntoInnerError`
+/// has a real error slot to carry the day this io layer grows fallible
+/// writes, without `into_inner`'s callers having to change their code then.
+pub enum WriteError {}
+
+/// Returned by [`BufferedWriter::into_inner`] when the final flush fails,
+/// bundling the error together with the original `T` so any bytes still
+/// buffered inside it are not lost.
+pub struct IntoInnerError<T>(T, WriteError);
+
+impl<T> IntoInnerError<T> {
+    /// Returns the error that occurred during the final flush.
+    pub fn error<'a>(&'a self) -> &'a WriteError {
+        let IntoInnerError(_, ref err) = *self;
+        err
+    }
+
+    /// Returns the original object, so any buffered data is not lost.
+    pub fn into_inner(self) -> T {
+        let IntoInnerError(t, _) = self;
+        t
+    }
+}
+
 /// Wraps a Writer and buffers output to it
 ///
-/// Note that `BufferedWriter` will NOT flush its buffer when dropped.
+/// This writer is flushed when dropped, so the last buffered bytes are not
+/// silently lost when a `BufferedWriter` goes out of scope without an
+/// explicit call to `flush`.
 pub struct BufferedWriter<W> {
-    priv inner: W,
+    priv inner: Option<W>,
     priv buf: ~[u8],
     priv pos: uint
 }
@@ -156,7 +271,7 @@ impl<W: Writer> BufferedWriter<W> {
         let mut buf = vec::with_capacity(cap);
         unsafe { vec::raw::set_len(&mut buf, cap); }
         BufferedWriter {
-            inner: inner,
+            inner: Some(inner),
             buf: buf,
             pos: 0
         }
@@ -169,10 +284,23 @@ impl<W: Writer> BufferedWriter<W> {
 
     fn flush_buf(&mut self) {
         if self.pos != 0 {
-            self.inner.write(self.buf.slice_to(self.pos));
+            self.inner.get_mut_ref().write(self.buf.slice_to(self.pos));
             self.pos = 0;
         }
     }
+
+    /// Unwraps this `BufferedWriter`, flushing any buffered bytes. On a
+    /// failed flush, the error and this `BufferedWriter` (buffered bytes
+    /// intact) are handed back instead of panicking or silently dropping
+    /// the data.
+    ///
+    /// `Writer::flush` can't actually fail in this io layer, so this will
+    /// always return `Ok`; the fallible signature exists so callers already
+    /// match on it and are ready for the day flushing can fail.
+    pub fn into_inner(mut self) -> Result<W, IntoInnerError<BufferedWriter<W>>> {
+        self.flush_buf();
+        Ok(self.inner.take_unwrap())
+    }
 }
 
 impl<W: Writer> Writer for BufferedWriter<W> {
@@ -182,7 +310,7 @@ impl<W: Writer> Writer for BufferedWriter<W> {
         }
 
         if buf.len() > self.buf.len() {
-            self.inner.write(buf);
+            self.inner.get_mut_ref().write(buf);
         } else {
             let dst = self.buf.mut_slice_from(self.pos);
             vec::bytes::copy_memory(dst, buf, buf.len());
@@ -192,20 +320,40 @@ impl<W: Writer> Writer for BufferedWriter<W> {
 
     fn flush(&mut self) {
         self.flush_buf();
-        self.inner.flush();
+        self.inner.get_mut_ref().flush();
     }
 }
 
 impl<W: Writer> Decorator<W> for BufferedWriter<W> {
-    fn inner(mut self) -> W { self.flush_buf(); self.inner }
-    fn inner_ref<'a>(&'a self) -> &'a W { &self.inner }
-    fn inner_mut_ref<'a>(&'a mut self) -> &'a mut W { &mut self.inner }
+    fn inner(self) -> W {
+        // `into_inner`'s flush can't actually fail in this io layer (see
+        // its doc comment), so the guaranteed-flush-or-recover path always
+        // succeeds here too.
+        match self.into_inner() {
+            Ok(w) => w,
+            Err(..) => fail!("flush failed"),
+        }
+    }
+    fn inner_ref<'a>(&'a self) -> &'a W { self.inner.get_ref() }
+    fn inner_mut_ref<'a>(&'a mut self) -> &'a mut W { self.inner.get_mut_ref() }
+}
+
+#[unsafe_destructor]
+impl<W: Writer> Drop for BufferedWriter<W> {
+    fn drop(&mut self) {
+        // `into_inner` already took `self.inner`, nothing left to flush.
+        if self.inner.is_some() {
+            self.flush_buf();
+        }
+    }
 }
 
 /// Wraps a Writer and buffers output to it, flushing whenever a newline (0xa,
 /// '\n') is detected.
 ///
-/// Note that this structure does NOT flush the output when dropped.
+/// Like `BufferedWriter`, this structure writes any buffered output to its
+/// underlying writer when dropped (the underlying writer's own `flush` is
+/// not called).
 pub struct LineBufferedWriter<W> {
     priv inner: BufferedWriter<W>,
 }
@@ -241,6 +389,116 @@ impl<W: Writer> Decorator<W> for LineBufferedWriter<W> {
     fn inner_mut_ref<'a>(&'a mut self) -> &'a mut W { self.inner.inner_mut_ref() }
 }
 
+/// Whether a `StdWriter` is treated as line buffered (flushing on every
+/// newline) or block buffered (flushing only when full or told to).
+#[deriving(Eq)]
+pub enum BufferingMode {
+    /// Flush after every newline, as is typical for an interactive terminal.
+    LineBuffered,
+    /// Flush only when the buffer fills up or `flush` is called explicitly.
+    BlockBuffered,
+}
+
+/// A type whose `isatty` method reports whether it is attached to an
+/// interactive terminal.
+///
+/// This crate has no fd-backed `Writer` of its own (no `stdio`/fd wrapper
+/// lives in this io layer), so nothing here implements `IsTty` against a
+/// real file descriptor. Callers that want `StdWriter::new` to actually
+/// reflect whether a stream is a terminal must implement `IsTty` for their
+/// own fd-backed writer, wiring `isatty` to their platform's `isatty(3)` (or
+/// equivalent); `StdWriter` only provides the mode-selection logic on top of
+/// whatever answer `isatty` gives.
+pub trait IsTty {
+    /// Returns true if this stream is attached to a terminal.
+    fn isatty(&self) -> bool;
+}
+
+/// Wraps a Writer and picks between line buffering and block buffering
+/// depending on whether `inner` is attached to a terminal, the way C's
+/// stdio picks `stdout`'s buffering mode. Which mode is active can always
+/// be forced with `set_mode`, regardless of how the `StdWriter` was
+/// constructed.
+///
+/// "Depending on whether `inner` is attached to a terminal" means exactly
+/// what `inner.isatty()` reports — see `IsTty`'s doc comment for the
+/// caveat that this crate has no real fd-backed `isatty` of its own to
+/// offer, so the caller's `W: IsTty` impl is what does the actual fd
+/// consultation.
+pub enum StdWriter<W> {
+    priv Line(LineBufferedWriter<W>),
+    priv Block(BufferedWriter<W>),
+}
+
+impl<W: Writer + IsTty> StdWriter<W> {
+    /// Creates a new `StdWriter`, choosing line buffering if `inner.isatty()`
+    /// reports a tty and block buffering otherwise.
+    pub fn new(inner: W) -> StdWriter<W> {
+        let mode = if inner.isatty() { LineBuffered } else { BlockBuffered };
+        StdWriter::with_mode(mode, inner)
+    }
+}
+
+impl<W: Writer> StdWriter<W> {
+    /// Creates a new `StdWriter` that unconditionally uses the given
+    /// buffering mode.
+    pub fn with_mode(mode: BufferingMode, inner: W) -> StdWriter<W> {
+        match mode {
+            LineBuffered => Line(LineBufferedWriter::new(inner)),
+            BlockBuffered => Block(BufferedWriter::new(inner)),
+        }
+    }
+
+    /// Forces this writer into the given buffering mode, flushing first so
+    /// no buffered bytes are lost across the transition.
+    pub fn set_mode(self, mode: BufferingMode) -> StdWriter<W> {
+        let inner = match self {
+            Line(w) => w.inner(),
+            Block(w) => w.inner(),
+        };
+        StdWriter::with_mode(mode, inner)
+    }
+}
+
+impl<W: Writer> Writer for StdWriter<W> {
+    fn write(&mut self, buf: &[u8]) {
+        match *self {
+            Line(ref mut w) => w.write(buf),
+            Block(ref mut w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) {
+        match *self {
+            Line(ref mut w) => w.flush(),
+            Block(ref mut w) => w.flush(),
+        }
+    }
+}
+
+impl<W: Writer> Decorator<W> for StdWriter<W> {
+    fn inner(self) -> W {
+        match self {
+            Line(w) => w.inner(),
+            Block(w) => w.inner(),
+        }
+    }
+
+    fn inner_ref<'a>(&'a self) -> &'a W {
+        match *self {
+            Line(ref w) => w.inner_ref(),
+            Block(ref w) => w.inner_ref(),
+        }
+    }
+
+    fn inner_mut_ref<'a>(&'a mut self) -> &'a mut W {
+        match *self {
+            Line(ref mut w) => w.inner_mut_ref(),
+            Block(ref mut w) => w.inner_mut_ref(),
+        }
+    }
+}
+
 struct InternalBufferedWriter<W>(BufferedWriter<W>);
 
 impl<W: Reader> Reader for InternalBufferedWriter<W> {
@@ -250,7 +508,8 @@ impl<W: Reader> Reader for InternalBufferedWriter<W> {
 
 /// Wraps a Stream and buffers input and output to and from it
 ///
-/// Note that `BufferedStream` will NOT flush its output buffer when dropped.
+/// Like `BufferedWriter`, the output side of this stream is flushed when
+/// dropped.
 pub struct BufferedStream<S> {
     priv inner: BufferedReader<InternalBufferedWriter<S>>
 }
@@ -269,11 +528,26 @@ impl<S: Stream> BufferedStream<S> {
         BufferedStream::with_capacities(DEFAULT_CAPACITY, DEFAULT_CAPACITY,
                                         inner)
     }
+
+    /// Converts this stream into an iterator over its lines, decoded as
+    /// UTF-8.
+    pub fn lines(self) -> Lines<BufferedStream<S>> {
+        Lines { buffer: self }
+    }
+
+    /// Converts this stream into an iterator over `byte`-delimited chunks.
+    pub fn split(self, byte: u8) -> Split<BufferedStream<S>> {
+        Split { buffer: self, delim: byte }
+    }
+
 }
 
 impl<S: Stream> Buffer for BufferedStream<S> {
     fn fill<'a>(&'a mut self) -> &'a [u8] { self.inner.fill() }
     fn consume(&mut self, amt: uint) { self.inner.consume(amt) }
+
+    fn buffer_len(&self) -> uint { self.inner.buffer_len() }
+    fn capacity(&self) -> uint { self.inner.capacity() }
 }
 
 impl<S: Stream> Reader for BufferedStream<S> {
@@ -298,7 +572,9 @@ impl<S: Stream> Decorator<S> for BufferedStream<S> {
 mod test {
     use prelude::*;
     use super::*;
+    use cell::RefCell;
     use io;
+    use rc::Rc;
     use super::super::mem::{MemReader, MemWriter};
     use Harness = extra::test::BenchHarness;
 
@@ -400,6 +676,77 @@ mod test {
         assert_eq!([0, 1], w.inner_ref().as_slice());
     }
 
+    struct SharedWriter(Rc<RefCell<~[u8]>>);
+
+    impl Writer for SharedWriter {
+        fn write(&mut self, buf: &[u8]) {
+            let SharedWriter(ref dst) = *self;
+            dst.borrow_mut().push_all(buf);
+        }
+        fn flush(&mut self) {}
+    }
+
+    #[test]
+    fn test_buffered_writer_flushes_on_drop() {
+        let dst = Rc::new(RefCell::new(~[]));
+        {
+            let mut w = BufferedWriter::with_capacity(16, SharedWriter(dst.clone()));
+            w.write([0, 1, 2]);
+            assert_eq!(dst.borrow().as_slice(), []);
+        }
+        assert_eq!(dst.borrow().as_slice(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_buffered_writer_into_inner() {
+        let mut w = BufferedWriter::with_capacity(4, MemWriter::new());
+        w.write([0, 1]);
+        let inner = w.into_inner().ok().unwrap();
+        assert_eq!(inner.inner_ref().as_slice(), [0, 1]);
+    }
+
+    #[test]
+    fn test_buffered_reader_peek() {
+        let inner = MemReader::new(~[0, 1, 2, 3, 4]);
+        let mut reader = BufferedReader::with_capacity(4, inner);
+
+        assert_eq!(reader.buffer_len(), 0);
+        assert_eq!(reader.capacity(), 4);
+
+        assert_eq!(reader.peek(2), [0, 1]);
+        // peek doesn't consume, so the full window is still there
+        assert_eq!(reader.buffer_len(), 4);
+        assert_eq!(reader.peek(10), [0, 1, 2, 3]);
+
+        assert_eq!(reader.read_byte(), Some(0));
+        assert_eq!(reader.buffer_len(), 3);
+    }
+
+    #[test]
+    fn test_buffered_reader_seek() {
+        let inner = MemReader::new(~[0, 1, 2, 3, 4, 5, 6, 7]);
+        let mut reader = BufferedReader::with_capacity(4, inner);
+
+        assert_eq!(reader.read_byte(), Some(0));
+        assert_eq!(reader.tell(), 1);
+
+        // A forward SeekCur within the buffered window doesn't touch the
+        // inner reader's position.
+        reader.seek(2, SeekCur);
+        assert_eq!(reader.tell(), 3);
+        assert_eq!(reader.read_byte(), Some(3));
+
+        // A seek outside of the buffered window discards the buffer and
+        // reissues the seek against the inner reader.
+        reader.seek(0, SeekSet);
+        assert_eq!(reader.tell(), 0);
+        assert_eq!(reader.read_byte(), Some(0));
+
+        reader.seek(-1, SeekEnd);
+        assert_eq!(reader.tell(), 7);
+        assert_eq!(reader.read_byte(), Some(7));
+    }
+
     // This is just here to make sure that we don't infinite loop in the
     // newtype struct autoderef weirdness
     #[test]
@@ -434,6 +781,31 @@ mod test {
         assert_eq!(reader.read_until(9), None);
     }
 
+    #[test]
+    fn test_read_line() {
+        let in_buf = MemReader::new(bytes!("a\nb\n").to_owned());
+        let mut reader = BufferedReader::with_capacity(2, in_buf);
+        assert_eq!(reader.read_line(), Some(~"a\n"));
+        assert_eq!(reader.read_line(), Some(~"b\n"));
+        assert_eq!(reader.read_line(), None);
+    }
+
+    #[test]
+    fn test_lines() {
+        let in_buf = MemReader::new(bytes!("a\nb\nc").to_owned());
+        let reader = BufferedReader::with_capacity(2, in_buf);
+        let lines: ~[~str] = reader.lines().collect();
+        assert_eq!(lines, ~[~"a\n", ~"b\n", ~"c"]);
+    }
+
+    #[test]
+    fn test_split() {
+        let in_buf = MemReader::new(~[1, 2, 0, 3, 4, 0, 5]);
+        let reader = BufferedReader::with_capacity(2, in_buf);
+        let chunks: ~[~[u8]] = reader.split(0).collect();
+        assert_eq!(chunks, ~[~[1, 2], ~[3, 4], ~[5]]);
+    }
+
     #[test]
     fn test_line_buffer() {
         let mut writer = LineBufferedWriter::new(MemWriter::new());
@@ -454,6 +826,53 @@ mod test {
             ~[0, 1, 0, '\n' as u8, 1, '\n' as u8, 2, 3, '\n' as u8]);
     }
 
+    struct FakeTty(MemWriter, bool);
+
+    impl Writer for FakeTty {
+        fn write(&mut self, buf: &[u8]) {
+            let FakeTty(ref mut w, _) = *self;
+            w.write(buf)
+        }
+        fn flush(&mut self) {
+            let FakeTty(ref mut w, _) = *self;
+            w.flush()
+        }
+    }
+
+    impl IsTty for FakeTty {
+        fn isatty(&self) -> bool {
+            let FakeTty(_, tty) = *self;
+            tty
+        }
+    }
+
+    fn fake_tty_bytes(writer: &StdWriter<FakeTty>) -> ~[u8] {
+        let FakeTty(ref mw, _) = *writer.inner_ref();
+        mw.inner_ref().clone()
+    }
+
+    #[test]
+    fn test_std_writer_picks_line_buffering_for_a_tty() {
+        let mut writer = StdWriter::new(FakeTty(MemWriter::new(), true));
+        writer.write([0, '\n' as u8, 1]);
+        assert_eq!(fake_tty_bytes(&writer), ~[0, '\n' as u8]);
+    }
+
+    #[test]
+    fn test_std_writer_picks_block_buffering_for_a_non_tty() {
+        let mut writer = StdWriter::new(FakeTty(MemWriter::new(), false));
+        writer.write([0, '\n' as u8, 1]);
+        assert_eq!(fake_tty_bytes(&writer), ~[]);
+    }
+
+    #[test]
+    fn test_std_writer_set_mode_overrides_isatty() {
+        let writer = StdWriter::new(FakeTty(MemWriter::new(), false));
+        let mut writer = writer.set_mode(LineBuffered);
+        writer.write([0, '\n' as u8, 1]);
+        assert_eq!(fake_tty_bytes(&writer), ~[0, '\n' as u8]);
+    }
+
     #[bench]
     fn bench_buffered_reader(bh: &mut Harness) {
         bh.iter(|| {
@@ -0,0 +1,116 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Core I/O traits shared by the readers, writers, and buffering wrappers
+//! in this module.
+
+use prelude::*;
+
+use num;
+use str;
+
+pub mod buffered;
+
+/// An enumeration of the possible relative positions a `seek` can be anchored to.
+#[deriving(Eq)]
+pub enum SeekStyle {
+    /// Seeks from the start of the stream
+    SeekSet,
+    /// Seeks from the end of the stream
+    SeekEnd,
+    /// Seeks from the current position
+    SeekCur,
+}
+
+/// An object implementing `Seek` is able to tell its current position in the
+/// stream and to move itself to another position within the stream.
+pub trait Seek {
+    /// Returns the current position in the stream
+    fn tell(&self) -> u64;
+
+    /// Seek to a given `pos`, relative to the anchor described by `style`
+    fn seek(&mut self, pos: i64, style: SeekStyle);
+}
+
+/// A trait for objects which are byte-oriented streams that carry their own
+/// internal buffer, so higher-level reading (searching for a delimiter,
+/// decoding a line, inspecting what's already available) can be built once
+/// here instead of being hand-rolled by every buffering wrapper.
+///
+/// Implementors provide `fill`, `consume`, `buffer_len` and `capacity`;
+/// `peek`, `read_until` and `read_line` come for free on top of `fill` and
+/// `consume`.
+pub trait Buffer: Reader {
+    /// Fills the internal buffer of this object, returning the slice of
+    /// bytes that are currently available. If the buffer is non-empty this
+    /// simply returns the existing contents; otherwise more data is read
+    /// from the underlying stream first.
+    fn fill<'a>(&'a mut self) -> &'a [u8];
+
+    /// Tells this buffer that `amt` bytes have been consumed from the front
+    /// of its buffer, so they will not be returned by future calls to
+    /// `fill`.
+    fn consume(&mut self, amt: uint);
+
+    /// Returns the number of bytes currently buffered but not yet consumed.
+    fn buffer_len(&self) -> uint;
+
+    /// Returns the total capacity of this buffer's internal storage.
+    fn capacity(&self) -> uint;
+
+    /// Fills the buffer if it's empty and returns up to the first `n`
+    /// buffered bytes, without consuming them. Useful for inspecting a
+    /// short header before deciding how to frame a message.
+    fn peek<'a>(&'a mut self, n: uint) -> &'a [u8] {
+        let available = self.fill();
+        available.slice_to(num::min(n, available.len()))
+    }
+
+    /// Reads bytes from this buffer until the specified byte is seen,
+    /// returning the bytes read, inclusive of the delimiter byte. Returns
+    /// `None` if no bytes were read before EOF.
+    fn read_until(&mut self, byte: u8) -> Option<~[u8]> {
+        let mut res = ~[];
+        let mut used;
+        loop {
+            {
+                let available = self.fill();
+                if available.len() == 0 {
+                    used = 0;
+                    break
+                }
+                match available.iter().position(|&b| b == byte) {
+                    Some(i) => {
+                        res.push_all(available.slice_to(i + 1));
+                        used = i + 1;
+                        break
+                    }
+                    None => {
+                        res.push_all(available);
+                        used = available.len();
+                    }
+                }
+            }
+            self.consume(used);
+        }
+        self.consume(used);
+        if res.len() == 0 {None} else {Some(res)}
+    }
+
+    /// Reads the next line of input, including the terminating `\n`,
+    /// decoding it as UTF-8. Returns `None` at EOF or if the bytes read are
+    /// not valid UTF-8.
+    ///
+    /// Built only on top of `read_until`, so it works the same for any
+    /// `Buffer` implementor.
+    fn read_line(&mut self) -> Option<~str> {
+        self.read_until('\n' as u8).and_then(|line| str::from_utf8_owned_opt(line))
+    }
+}